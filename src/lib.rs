@@ -1,3 +1,7 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
 /// Holds an expression in a vector of `Tokens` along with the original (white-space-trimmed) expression.
 #[derive(Debug, PartialEq)]
 pub struct Expression {
@@ -5,42 +9,180 @@ pub struct Expression {
     tokens: Vec<Token>,
 }
 
+/// Errors that can occur while tokenizing, parsing or evaluating an [Expression].
+#[derive(Debug, PartialEq)]
+pub enum MathError {
+    /// A character was encountered that is not part of the supported grammar.
+    InvalidToken(char),
+    /// The expression contains a `(` or `)` without a matching counterpart.
+    UnbalancedParens,
+    /// An operator was applied without enough operands on the stack.
+    MissingOperand,
+    /// A division by zero was attempted.
+    DivisionByZero,
+    /// The expression contained no tokens to evaluate.
+    EmptyExpression,
+    /// An identifier was used that does not name a known function.
+    UnknownFunction(String),
+    /// The expression uses an operator the stack machine cannot compile to bytecode.
+    CannotCompile,
+    /// A bitwise operator was applied to a non-integral operand.
+    NonIntegerOperand,
+    /// A shift by an amount outside `0..64` was requested.
+    ShiftOutOfRange,
+    /// An identifier was referenced that is not bound in the environment.
+    UndefinedVariable(String),
+}
+
+impl fmt::Display for MathError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidToken(ch) => write!(f, "'{}' is not a supported token.", ch),
+            Self::UnbalancedParens => write!(f, "the expression has unbalanced brackets."),
+            Self::MissingOperand => write!(f, "an operator is missing an operand."),
+            Self::DivisionByZero => write!(f, "cannot divide by zero."),
+            Self::EmptyExpression => write!(f, "the expression is empty."),
+            Self::UnknownFunction(name) => write!(f, "'{}' is not a known function.", name),
+            Self::CannotCompile => {
+                write!(f, "the expression cannot be compiled to bytecode.")
+            }
+            Self::NonIntegerOperand => {
+                write!(f, "bitwise operators require integral operands.")
+            }
+            Self::ShiftOutOfRange => {
+                write!(f, "the shift amount must be in the range 0..64.")
+            }
+            Self::UndefinedVariable(name) => write!(f, "'{}' is not defined.", name),
+        }
+    }
+}
+
+impl Error for MathError {}
+
 #[derive(Debug, PartialEq)]
 enum Token {
-    Number(u64), // floats should be represented as devisions and negative numbers are represented as operations
+    Number(f64),
     Plus,
     Min,
     Prod,
     Dev,
+    Pow,
+    Neg, // unary negation, e.g. the leading `-` in `-5`
+    Func(FuncKind),
+    Ident(String),
+    BitAnd,
+    BitOr,
+    BitXor,
+    Shl,
+    Shr,
     Left,
     Right,
 }
 
+/// A built-in single-argument math function.
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum FuncKind {
+    Sin,
+    Cos,
+    Tan,
+    Sqrt,
+    Ln,
+    Log,
+    Abs,
+}
+
+impl FuncKind {
+    /// Maps an identifier run onto a built-in function, returning `None` when it is not known.
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "sin" => Some(Self::Sin),
+            "cos" => Some(Self::Cos),
+            "tan" => Some(Self::Tan),
+            "sqrt" => Some(Self::Sqrt),
+            "ln" => Some(Self::Ln),
+            "log" => Some(Self::Log),
+            "abs" => Some(Self::Abs),
+            _ => None,
+        }
+    }
+
+    /// Applies the function to a single operand.
+    fn apply(&self, x: f64) -> f64 {
+        match self {
+            Self::Sin => x.sin(),
+            Self::Cos => x.cos(),
+            Self::Tan => x.tan(),
+            Self::Sqrt => x.sqrt(),
+            Self::Ln => x.ln(),
+            Self::Log => x.log10(),
+            Self::Abs => x.abs(),
+        }
+    }
+}
+
+/// The associativity of an operator, used to decide when to pop equal-precedence
+/// operators off the stack in the shunting-yard algorithm.
+#[derive(Debug, PartialEq)]
+enum Associativity {
+    Left,
+    Right,
+}
+
+/// One of the four general-purpose registers of the [exec] stack machine.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Reg {
+    Ax,
+    Bx,
+    Cx,
+    Dx,
+}
+
+/// A single instruction of the tiny register-based stack machine an [Expression] compiles to.
+///
+/// An ALU op `Sub(dst, src)` computes `dst = dst - src` in place; the other arithmetic
+/// instructions behave analogously.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Instr {
+    /// Push an immediate value onto the value stack.
+    Push(f64),
+    /// Push the contents of a register onto the value stack.
+    PushReg(Reg),
+    /// Pop the top of the value stack into a register.
+    Pop(Reg),
+    Add(Reg, Reg),
+    Sub(Reg, Reg),
+    Mul(Reg, Reg),
+    Div(Reg, Reg),
+}
+
 impl Expression {
     /// Creates a new expression from the given string slice.
     /// Tokenizes it and stores it in postfix form using the [Shunting-Yard algorithm](https://en.wikipedia.org/wiki/Shunting-yard_algorithm)
     ///
-    /// # Panics
-    ///
-    /// Panics when the given expression contains invalid tokens.
+    /// # Errors
     ///
-    /// # Todo
-    /// Check if expression has balanced brackets.
-    pub fn new(expr: &str) -> Self {
+    /// Returns [MathError::InvalidToken] when the expression contains an unsupported character,
+    /// [MathError::UnbalancedParens] when its brackets do not match and [MathError::EmptyExpression]
+    /// when the expression contains no tokens.
+    pub fn new(expr: &str) -> Result<Self, MathError> {
         let expr: String = expr.chars().filter(|ch| *ch != ' ').collect();
 
-        let tokens = Self::tokenize(&expr);
+        if expr.is_empty() {
+            return Err(MathError::EmptyExpression);
+        }
+
+        let tokens = Self::tokenize(&expr)?;
         // println!("After tokenize: {:?}", tokens);
-        let tokens = Self::to_post(tokens);
+        let tokens = Self::to_post(tokens)?;
         // println!("After to_post: {:?}", tokens);
 
-        Self {
+        Ok(Self {
             original: expr.to_owned(),
             tokens,
-        }
+        })
     }
 
-    fn tokenize(expr: &str) -> Vec<Token> {
+    fn tokenize(expr: &str) -> Result<Vec<Token>, MathError> {
         // tokenize the string slice and
         // add multiplication signs where needed
         let mut iter = expr.chars().peekable();
@@ -50,80 +192,170 @@ impl Expression {
         while let Some(ch) = iter.next() {
             match ch {
                 '0'..='9' => {
+                    // radix-prefixed integer literals: `0x`, `0b`, `0o`.
+                    if ch == '0' {
+                        let radix = match iter.peek() {
+                            Some('x') => Some(16),
+                            Some('b') => Some(2),
+                            Some('o') => Some(8),
+                            _ => None,
+                        };
+
+                        if let Some(radix) = radix {
+                            let prefix = iter.next().unwrap();
+
+                            let mut digits = String::new();
+                            while let Some(c) = iter.peek() {
+                                if c.is_digit(radix) {
+                                    digits.push(iter.next().unwrap());
+                                } else {
+                                    break;
+                                }
+                            }
+
+                            let value = u64::from_str_radix(&digits, radix)
+                                .map_err(|_| MathError::InvalidToken(prefix))?;
+                            tokens.push(Token::Number(value as f64));
+
+                            if let Some('(') = iter.peek() {
+                                tokens.push(Token::Prod);
+                            }
+
+                            continue;
+                        }
+                    }
+
                     let mut curr_number = String::from(ch);
+                    let mut seen_dot = false;
 
-                    // check if number is longer than one char
+                    // consume the rest of the number: further digits plus an optional
+                    // single decimal point. A second `.` is not a valid number.
                     loop {
                         match iter.peek() {
                             Some('0'..='9') => curr_number.push(iter.next().unwrap()),
+                            Some('.') => {
+                                if seen_dot {
+                                    return Err(MathError::InvalidToken('.'));
+                                }
+                                seen_dot = true;
+                                curr_number.push(iter.next().unwrap());
+                            }
                             _ => break,
                         }
                     }
 
-                    tokens.push(Token::Number(curr_number.parse().unwrap()));
+                    let number = curr_number
+                        .parse()
+                        .map_err(|_| MathError::InvalidToken('.'))?;
+                    tokens.push(Token::Number(number));
 
                     // check if next char needs to be a multiplication
-                    match iter.peek() {
-                        Some('(') => {
-                            tokens.push(Token::Prod);
-                        },
-                        _ => (),
+                    if let Some('(') = iter.peek() {
+                        tokens.push(Token::Prod);
                     }
                 },
                 '*' => tokens.push(Token::Prod),
                 '/' => tokens.push(Token::Dev),
+                '^' => {
+                    // `^^` is bitwise xor, a single `^` is exponentiation.
+                    if let Some('^') = iter.peek() {
+                        iter.next();
+                        tokens.push(Token::BitXor);
+                    } else {
+                        tokens.push(Token::Pow);
+                    }
+                }
+                '&' => tokens.push(Token::BitAnd),
+                '|' => tokens.push(Token::BitOr),
+                '<' => match iter.next() {
+                    Some('<') => tokens.push(Token::Shl),
+                    _ => return Err(MathError::InvalidToken('<')),
+                },
+                '>' => match iter.next() {
+                    Some('>') => tokens.push(Token::Shr),
+                    _ => return Err(MathError::InvalidToken('>')),
+                },
                 '+' => tokens.push(Token::Plus),
                 '-' => {
-                    // check if it is a multiplication with -1
-                    let mult = if let Some(next) = iter.peek() {
-                        match next {
-                            '(' => true,
-                            _ => false,
-                        }
+                    // a `-` is unary negation at the start of the expression or directly
+                    // after another operator or an opening bracket, and binary subtraction
+                    // otherwise.
+                    let unary = matches!(
+                        tokens.last(),
+                        None | Some(
+                            Token::Plus
+                                | Token::Min
+                                | Token::Prod
+                                | Token::Dev
+                                | Token::Pow
+                                | Token::Neg
+                                | Token::Func(_)
+                                | Token::BitAnd
+                                | Token::BitOr
+                                | Token::BitXor
+                                | Token::Shl
+                                | Token::Shr
+                                | Token::Left
+                        )
+                    );
+
+                    if unary {
+                        tokens.push(Token::Neg);
                     } else {
-                        false
-                    };
-
-                    if !mult {
                         tokens.push(Token::Min);
-                    } else {    // '-' before '(' is seen as '...+(0-1)*(...)' because numbers cannot be stored negative
-                        // TODO: Store negative numbers
-                        tokens.push(Token::Plus);
-                        tokens.push(Token::Left);
-                        tokens.push(Token::Number(0));
-                        tokens.push(Token::Min);
-                        tokens.push(Token::Number(1));
-                        tokens.push(Token::Right);
-                        tokens.push(Token::Prod);
                     }
                 },
+                'a'..='z' => {
+                    // read the whole identifier run.
+                    let mut name = String::from(ch);
+                    while let Some('a'..='z') = iter.peek() {
+                        name.push(iter.next().unwrap());
+                    }
+
+                    // an identifier directly applied to a `(` is a function call and must name a
+                    // known function; otherwise it is a variable or named constant.
+                    if let Some('(') = iter.peek() {
+                        let kind = FuncKind::from_name(&name)
+                            .ok_or(MathError::UnknownFunction(name))?;
+                        tokens.push(Token::Func(kind));
+                    } else {
+                        tokens.push(Token::Ident(name));
+                    }
+                }
                 '(' => tokens.push(Token::Left),
                 ')' => tokens.push(Token::Right),
-                other => panic!("The expression '{}' is not valid, because token '{}' is not a supported token.", expr, other),
+                other => return Err(MathError::InvalidToken(other)),
             }
         }
 
-        tokens
+        Ok(tokens)
     }
 
     /// Converts vector of tokens to vector of tokens in postfix form using the [Shunting-Yard algorithm](https://en.wikipedia.org/wiki/Shunting-yard_algorithm)
-    fn to_post(original: Vec<Token>) -> Vec<Token> {
+    fn to_post(original: Vec<Token>) -> Result<Vec<Token>, MathError> {
         let mut tokens: Vec<Token> = Vec::new();
         let mut operator_stack: Vec<Token> = Vec::new();
 
         for token in original.into_iter() {
             match token {
-                Token::Number(_) => tokens.push(token),
+                Token::Number(_) | Token::Ident(_) => tokens.push(token),
+                Token::Func(_) => operator_stack.push(token),
                 Token::Left => operator_stack.push(token),
                 Token::Right => {
-                    while !operator_stack.is_empty() {
-                        let top = operator_stack.pop().unwrap();
-
-                        match top {
-                            Token::Left => break,
-                            other => tokens.push(other),
+                    // pop operators back to the matching `Left`; running out first
+                    // means there is a `)` without an opening `(`.
+                    loop {
+                        match operator_stack.pop() {
+                            Some(Token::Left) => break,
+                            Some(other) => tokens.push(other),
+                            None => return Err(MathError::UnbalancedParens),
                         }
                     }
+
+                    // a function applied to the just-closed group is popped to the output too.
+                    if let Some(Token::Func(_)) = operator_stack.last() {
+                        tokens.push(operator_stack.pop().unwrap());
+                    }
                 }
                 operator => {
                     while !operator_stack.is_empty() {
@@ -133,7 +365,14 @@ impl Expression {
                             break;
                         }
 
-                        if Token::precedence(top) >= Token::precedence(&operator) {
+                        // pop operators of strictly higher precedence, and equal
+                        // precedence only when the incoming operator is left-associative
+                        // (right-associative operators such as `^` bind the later operand).
+                        let pop = Token::precedence(top) > Token::precedence(&operator)
+                            || (Token::precedence(top) == Token::precedence(&operator)
+                                && operator.associativity() == Associativity::Left);
+
+                        if pop {
                             tokens.push(operator_stack.pop().unwrap());
                         } else {
                             break;
@@ -145,30 +384,74 @@ impl Expression {
             }
         }
 
-        // empty the operator_stack
-        while !operator_stack.is_empty() {
-            tokens.push(operator_stack.pop().unwrap());
+        // empty the operator_stack; a leftover `Left` means an unclosed `(`.
+        while let Some(top) = operator_stack.pop() {
+            if top == Token::Left {
+                return Err(MathError::UnbalancedParens);
+            }
+            tokens.push(top);
         }
 
-        tokens
+        Ok(tokens)
+    }
+
+    /// Evaluates the expression with an empty environment and returns the result as an `f64`.
+    ///
+    /// Named constants (`pi`, `e`) are still available; see [Expression::evaluate_with] for
+    /// evaluating against user-defined variables.
+    ///
+    /// # Errors
+    ///
+    /// Returns [MathError::MissingOperand] when an operator runs out of operands and
+    /// [MathError::DivisionByZero] when a division by zero is attempted.
+    pub fn evaluate(&self) -> Result<f64, MathError> {
+        self.evaluate_with(&HashMap::new())
     }
 
-    /// Evaluates the expression and returns the result as an `f64`
-    pub fn evaluate(&self) -> f64 {
+    /// Evaluates the expression, resolving identifiers from `env`.
+    ///
+    /// The constants `pi` and `e` are seeded automatically and may be overridden by `env`.
+    ///
+    /// # Errors
+    ///
+    /// In addition to the errors of [Expression::evaluate], returns
+    /// [MathError::UndefinedVariable] when an identifier is bound neither in `env` nor as a
+    /// built-in constant.
+    pub fn evaluate_with(&self, env: &HashMap<String, f64>) -> Result<f64, MathError> {
         let mut stack: Vec<f64> = Vec::new();
 
         for token in self.tokens.iter() {
             match token {
-                Token::Number(n) => stack.push(n.clone() as f64),
+                Token::Number(n) => stack.push(*n),
+                Token::Ident(name) => stack.push(resolve(name, env)?),
+                Token::Neg => {
+                    let a = stack.pop().ok_or(MathError::MissingOperand)?;
+                    stack.push(-a);
+                }
+                Token::Func(kind) => {
+                    let a = stack.pop().ok_or(MathError::MissingOperand)?;
+                    stack.push(kind.apply(a));
+                }
                 operator => {
-                    let a = stack.pop().unwrap();
-                    let b = stack.pop().unwrap();
+                    let a = stack.pop().ok_or(MathError::MissingOperand)?;
+                    let b = stack.pop().ok_or(MathError::MissingOperand)?;
 
                     match operator {
-                        Token::Prod => stack.push((b * a) as f64),
-                        Token::Dev => stack.push((b / a) as f64),
-                        Token::Plus => stack.push((b + a) as f64),
-                        Token::Min => stack.push((b - a) as f64),
+                        Token::Prod => stack.push(b * a),
+                        Token::Dev => {
+                            if a == 0.0 {
+                                return Err(MathError::DivisionByZero);
+                            }
+                            stack.push(b / a)
+                        }
+                        Token::Plus => stack.push(b + a),
+                        Token::Min => stack.push(b - a),
+                        Token::Pow => stack.push(b.powf(a)),
+                        Token::BitAnd => stack.push((to_int(b)? & to_int(a)?) as f64),
+                        Token::BitOr => stack.push((to_int(b)? | to_int(a)?) as f64),
+                        Token::BitXor => stack.push((to_int(b)? ^ to_int(a)?) as f64),
+                        Token::Shl => stack.push(shift(to_int(b)?, to_int(a)?, false)? as f64),
+                        Token::Shr => stack.push(shift(to_int(b)?, to_int(a)?, true)? as f64),
                         other => panic!(
                             "Tried to use '{:?}' as ann operator when evaluating.",
                             other
@@ -178,7 +461,46 @@ impl Expression {
             }
         }
 
-        return stack.pop().unwrap();
+        stack.pop().ok_or(MathError::MissingOperand)
+    }
+
+    /// Lowers the postfix `tokens` into a flat instruction stream for the [exec] stack machine.
+    ///
+    /// Each number becomes a `Push`; each binary operator pops its right-hand operand into `Ax`
+    /// and its left-hand operand into `Bx`, applies the matching ALU op onto `Bx` and pushes the
+    /// result back, so compound subexpressions compose on the value stack.
+    ///
+    /// The machine only knows the four basic arithmetic operators; exponentiation is evaluated by
+    /// [Expression::evaluate].
+    ///
+    /// # Errors
+    ///
+    /// Returns [MathError::CannotCompile] when the expression uses an operator the stack machine
+    /// does not model (exponentiation, functions, variables, unary negation or bitwise operators).
+    pub fn compile(&self) -> Result<Vec<Instr>, MathError> {
+        let mut program: Vec<Instr> = Vec::new();
+
+        for token in self.tokens.iter() {
+            match token {
+                Token::Number(n) => program.push(Instr::Push(*n)),
+                operator => {
+                    let alu = match operator {
+                        Token::Plus => Instr::Add(Reg::Bx, Reg::Ax),
+                        Token::Min => Instr::Sub(Reg::Bx, Reg::Ax),
+                        Token::Prod => Instr::Mul(Reg::Bx, Reg::Ax),
+                        Token::Dev => Instr::Div(Reg::Bx, Reg::Ax),
+                        _ => return Err(MathError::CannotCompile),
+                    };
+
+                    program.push(Instr::Pop(Reg::Ax));
+                    program.push(Instr::Pop(Reg::Bx));
+                    program.push(alu);
+                    program.push(Instr::PushReg(Reg::Bx));
+                }
+            }
+        }
+
+        Ok(program)
     }
 
     /// Returns the original white space trimmed expression as `&str`.
@@ -187,14 +509,105 @@ impl Expression {
     }
 }
 
+impl Reg {
+    /// The position of this register in the machine's register file.
+    fn index(&self) -> usize {
+        match self {
+            Self::Ax => 0,
+            Self::Bx => 1,
+            Self::Cx => 2,
+            Self::Dx => 3,
+        }
+    }
+}
+
+/// Resolves an identifier against the environment, falling back to the built-in constants
+/// `pi` and `e` before reporting it as undefined.
+fn resolve(name: &str, env: &HashMap<String, f64>) -> Result<f64, MathError> {
+    if let Some(value) = env.get(name) {
+        return Ok(*value);
+    }
+
+    match name {
+        "pi" => Ok(std::f64::consts::PI),
+        "e" => Ok(std::f64::consts::E),
+        _ => Err(MathError::UndefinedVariable(name.to_owned())),
+    }
+}
+
+/// Shifts `value` by `amount` bits, rejecting shift amounts outside `0..64`.
+fn shift(value: i64, amount: i64, right: bool) -> Result<i64, MathError> {
+    if !(0..64).contains(&amount) {
+        return Err(MathError::ShiftOutOfRange);
+    }
+
+    Ok(if right {
+        value >> amount
+    } else {
+        value << amount
+    })
+}
+
+/// Converts an operand to an integer for a bitwise operator, rejecting non-integral values.
+fn to_int(x: f64) -> Result<i64, MathError> {
+    if x.fract() == 0.0 {
+        Ok(x as i64)
+    } else {
+        Err(MathError::NonIntegerOperand)
+    }
+}
+
+/// Runs a program produced by [Expression::compile] on the stack machine and returns its result.
+///
+/// # Errors
+///
+/// Returns [MathError::MissingOperand] when an instruction reads from an empty value stack and
+/// [MathError::DivisionByZero] when a `Div` divides by zero.
+pub fn exec(program: &[Instr]) -> Result<f64, MathError> {
+    let mut regs = [0.0f64; 4];
+    let mut stack: Vec<f64> = Vec::new();
+
+    for instr in program.iter() {
+        match instr {
+            Instr::Push(n) => stack.push(*n),
+            Instr::PushReg(r) => stack.push(regs[r.index()]),
+            Instr::Pop(r) => regs[r.index()] = stack.pop().ok_or(MathError::MissingOperand)?,
+            Instr::Add(dst, src) => regs[dst.index()] += regs[src.index()],
+            Instr::Sub(dst, src) => regs[dst.index()] -= regs[src.index()],
+            Instr::Mul(dst, src) => regs[dst.index()] *= regs[src.index()],
+            Instr::Div(dst, src) => {
+                if regs[src.index()] == 0.0 {
+                    return Err(MathError::DivisionByZero);
+                }
+                regs[dst.index()] /= regs[src.index()];
+            }
+        }
+    }
+
+    stack.pop().ok_or(MathError::MissingOperand)
+}
+
 impl Token {
     fn precedence(&self) -> usize {
         match *self {
-            Self::Prod | Self::Dev => 1,
-            Self::Plus | Self::Min => 0,
+            Self::Pow => 7,
+            Self::Neg => 6,
+            Self::Prod | Self::Dev => 5,
+            Self::Plus | Self::Min => 4,
+            Self::Shl | Self::Shr => 3,
+            Self::BitAnd => 2,
+            Self::BitXor => 1,
+            Self::BitOr => 0,
             _ => panic!("Precedence of token '{:?}' cannot be found.", self),
         }
     }
+
+    fn associativity(&self) -> Associativity {
+        match *self {
+            Self::Pow | Self::Neg => Associativity::Right,
+            _ => Associativity::Left,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -219,23 +632,17 @@ mod expression_tests {
 
     #[test]
     fn tokenize() {
-        let tokens = Expression::tokenize("12-(13+7)*3");
+        let tokens = Expression::tokenize("12-(13+7)*3").unwrap();
         let wanted_tokens = vec![
-            Number(12),
-            Plus,
-            Left,
-            Number(0),
+            Number(12.0),
             Min,
-            Number(1),
-            Right,
-            Prod,
             Left,
-            Number(13),
+            Number(13.0),
             Plus,
-            Number(7),
+            Number(7.0),
             Right,
             Prod,
-            Number(3),
+            Number(3.0),
         ];
 
         assert_eq!(wanted_tokens, tokens);
@@ -243,19 +650,16 @@ mod expression_tests {
 
     #[test]
     fn to_post() {
-        let tokens = Expression::to_post(Expression::tokenize("133+(15-(125/3)+1)"));
+        let tokens =
+            Expression::to_post(Expression::tokenize("133+(15-(125/3)+1)").unwrap()).unwrap();
         let wanted_tokens = vec![
-            Number(133),
-            Number(15),
-            Number(0),
-            Number(1),
-            Min,
-            Number(125),
-            Number(3),
+            Number(133.0),
+            Number(15.0),
+            Number(125.0),
+            Number(3.0),
             Dev,
-            Prod,
-            Plus,
-            Number(1),
+            Min,
+            Number(1.0),
             Plus,
             Plus,
         ];
@@ -265,28 +669,24 @@ mod expression_tests {
 
     #[test]
     fn new_expression() {
-        let expression = Expression::new("125-(145*9+3-2(12/3))-2");
+        let expression = Expression::new("125-(145*9+3-2(12/3))-2").unwrap();
         let wanted_expression = Expression {
             original: "125-(145*9+3-2(12/3))-2".to_owned(),
             tokens: vec![
-                Number(125),
-                Number(0),
-                Number(1),
-                Min,
-                Number(145),
-                Number(9),
+                Number(125.0),
+                Number(145.0),
+                Number(9.0),
                 Prod,
-                Number(3),
+                Number(3.0),
                 Plus,
-                Number(2),
-                Number(12),
-                Number(3),
+                Number(2.0),
+                Number(12.0),
+                Number(3.0),
                 Dev,
                 Prod,
                 Min,
-                Prod,
-                Plus,
-                Number(2),
+                Min,
+                Number(2.0),
                 Min,
             ],
         };
@@ -294,11 +694,142 @@ mod expression_tests {
         assert_eq!(wanted_expression, expression);
     }
 
+    #[test]
+    fn pow_is_right_associative() {
+        let tokens = Expression::to_post(Expression::tokenize("2^3^2").unwrap()).unwrap();
+        let wanted_tokens = vec![Number(2.0), Number(3.0), Number(2.0), Pow, Pow];
+
+        assert_eq!(wanted_tokens, tokens);
+        assert_eq!(
+            512.0,
+            Expression::new("2^3^2").unwrap().evaluate().unwrap()
+        );
+    }
+
+    #[test]
+    fn pow_binds_tighter_than_prod() {
+        let tokens = Expression::to_post(Expression::tokenize("2*3^2").unwrap()).unwrap();
+        let wanted_tokens = vec![Number(2.0), Number(3.0), Number(2.0), Pow, Prod];
+
+        assert_eq!(wanted_tokens, tokens);
+        assert_eq!(18.0, Expression::new("2*3^2").unwrap().evaluate().unwrap());
+    }
+
     #[test]
     fn evaluate_expression() {
-        let result = Expression::new("125-(145*9+3-2(12/3))-2").evaluate();
+        let result = Expression::new("125-(145*9+3-2(12/3))-2")
+            .unwrap()
+            .evaluate()
+            .unwrap();
         let wanted_result = -1177.0;
 
         assert_eq!(result, wanted_result);
     }
+
+    #[test]
+    fn float_and_signed_literals() {
+        assert_eq!(
+            "3.14".parse::<f64>().unwrap(),
+            Expression::new("3.14").unwrap().evaluate().unwrap()
+        );
+        assert_eq!(-5.0, Expression::new("-5").unwrap().evaluate().unwrap());
+        assert_eq!(5.0, Expression::new("2-(-3)").unwrap().evaluate().unwrap());
+        // unary negation binds looser than exponentiation: -2^2 == -(2^2)
+        assert_eq!(-4.0, Expression::new("-2^2").unwrap().evaluate().unwrap());
+    }
+
+    #[test]
+    fn second_decimal_point_is_invalid() {
+        assert_eq!(Err(super::MathError::InvalidToken('.')), Expression::new("1.2.3"));
+    }
+
+    #[test]
+    fn built_in_functions() {
+        assert_eq!(5.0, Expression::new("sqrt(16)+1").unwrap().evaluate().unwrap());
+        assert_eq!(0.0, Expression::new("2*sin(0)").unwrap().evaluate().unwrap());
+    }
+
+    #[test]
+    fn unknown_function_is_rejected() {
+        assert_eq!(
+            Err(super::MathError::UnknownFunction("foo".to_owned())),
+            Expression::new("foo(1)")
+        );
+    }
+
+    #[test]
+    fn bitwise_and_radix_literals() {
+        assert_eq!(0x0F as f64, Expression::new("0xFF & 0x0F").unwrap().evaluate().unwrap());
+        assert_eq!(16.0, Expression::new("1 << 4").unwrap().evaluate().unwrap());
+        assert_eq!(11.0, Expression::new("0b1010 | 3").unwrap().evaluate().unwrap());
+        // a `-` after a bitwise operator is unary negation, not subtraction
+        assert_eq!(0.0, Expression::new("5 & -1 & 0").unwrap().evaluate().unwrap());
+    }
+
+    #[test]
+    fn out_of_range_shift_is_rejected() {
+        assert_eq!(
+            Err(super::MathError::ShiftOutOfRange),
+            Expression::new("1 << 64").unwrap().evaluate()
+        );
+        assert_eq!(
+            Err(super::MathError::ShiftOutOfRange),
+            Expression::new("1 << -1").unwrap().evaluate()
+        );
+    }
+
+    #[test]
+    fn constants_and_variables() {
+        use std::collections::HashMap;
+
+        // built-in constants resolve through the plain evaluate wrapper
+        assert_eq!(
+            std::f64::consts::PI,
+            Expression::new("pi").unwrap().evaluate().unwrap()
+        );
+
+        // user-defined variables resolve through the environment
+        let mut env = HashMap::new();
+        env.insert("x".to_owned(), 7.0);
+        assert_eq!(
+            14.0,
+            Expression::new("x*2").unwrap().evaluate_with(&env).unwrap()
+        );
+    }
+
+    #[test]
+    fn undefined_variable_is_rejected() {
+        assert_eq!(
+            Err(super::MathError::UndefinedVariable("x".to_owned())),
+            Expression::new("x+1").unwrap().evaluate()
+        );
+    }
+
+    #[test]
+    fn compile_emits_expected_program() {
+        use super::{Instr::*, Reg::*};
+
+        let program = Expression::new("5-3").unwrap().compile().unwrap();
+        let wanted_program = vec![Push(5.0), Push(3.0), Pop(Ax), Pop(Bx), Sub(Bx, Ax), PushReg(Bx)];
+
+        assert_eq!(wanted_program, program);
+    }
+
+    #[test]
+    fn exec_matches_evaluate() {
+        for expr in ["5-3", "2+3*4", "125-(145*9+3-2(12/3))-2", "(1+2)/3"] {
+            let expression = Expression::new(expr).unwrap();
+            assert_eq!(
+                super::exec(&expression.compile().unwrap()).unwrap(),
+                expression.evaluate().unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn compile_rejects_unsupported_operators() {
+        assert_eq!(Err(super::MathError::CannotCompile), Expression::new("2^3").unwrap().compile());
+        assert_eq!(Err(super::MathError::CannotCompile), Expression::new("sqrt(4)").unwrap().compile());
+        assert_eq!(Err(super::MathError::CannotCompile), Expression::new("x+1").unwrap().compile());
+    }
 }
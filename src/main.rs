@@ -9,14 +9,17 @@
 //! It is my first ever completed project in rust as a means to develop some real world eperience.
 
 
-use lib::Expression;
+use std::collections::HashMap;
 
-mod lib;
+use mathelizer::Expression;
 
 fn main() {
+    // variables defined during the session persist across iterations
+    let mut env: HashMap<String, f64> = HashMap::new();
+
     loop {
         // ask for math expression
-        println!("Input any valid math expression without functions.");
+        println!("Input any valid math expression.");
 
         // get input from stdin and trim the whitespace from left and right
         let mut input = String::new();
@@ -28,10 +31,38 @@ fn main() {
             break;
         }
 
+        // a top-level `name = expr` binds `name` to the value of `expr` for the rest of the session
+        if let Some((name, rhs)) = input.split_once('=') {
+            let name = name.trim();
+
+            if name.is_empty() || !name.chars().all(|ch| ch.is_ascii_lowercase()) {
+                println!("Error: '{}' is not a valid variable name.", name);
+                continue;
+            }
+
+            match Expression::new(rhs).and_then(|expr| expr.evaluate_with(&env)) {
+                Ok(result) => {
+                    env.insert(name.to_owned(), result);
+                    println!("'{}' is now {}", name, result);
+                }
+                Err(err) => println!("Error: {}", err),
+            }
+
+            continue;
+        }
+
         // convert to math expression and return result
-        let expression = Expression::new(&input);
-        let result = expression.evaluate();
+        let expression = match Expression::new(input) {
+            Ok(expression) => expression,
+            Err(err) => {
+                println!("Error: {}", err);
+                continue;
+            }
+        };
 
-        println!("'{}' evaluates to: {}", expression.as_str(), result);
+        match expression.evaluate_with(&env) {
+            Ok(result) => println!("'{}' evaluates to: {}", expression.as_str(), result),
+            Err(err) => println!("Error: {}", err),
+        }
     }
 }